@@ -1,20 +1,297 @@
 // Based off of the great SimpleLogger crate: https://crates.io/crates/simple_logger
+use chrono::{DateTime, Utc};
 use colored::*;
 use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use regex::Regex;
 use rustc_hash::FxHashMap;
-use std::sync::{Arc, Mutex};
+use serde::{Serialize, Serializer};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use strip_ansi_escapes::strip;
 use termsize::Size;
 
 use crate::constants;
 
+/// Name of the environment variable consulted by [`TreeLogger::init`] when
+/// [`TreeLogger::with_filter_spec`] was never called explicitly.
+const TREE_LOG_ENV_VAR: &str = "TREE_LOG";
+
+/// Capacity of the channel feeding the background writer thread enabled by
+/// [`TreeLogger::with_async`]. `log()` blocks once this many completed
+/// batches are queued, which bounds memory use without data loss.
+const ASYNC_CHANNEL_CAPACITY: usize = 1024;
+
 pub struct TreeLogger {
     default_level: LevelFilter,
     threads_enabled: bool,
+    module_levels: Vec<(String, LevelFilter)>,
+    async_enabled: bool,
+    /// Set once `init()` spawns the background writer thread; `log()` hands
+    /// completed batches off to it instead of rendering them inline.
+    /// `SyncSender` is already `Send + Sync`, so no `Mutex` is needed here —
+    /// wrapping it in one would serialize every logging thread behind a
+    /// single lock whenever `send()` blocks on a full channel.
+    sender: Option<mpsc::SyncSender<WriterMessage>>,
+    /// Set by [`Self::with_memory_buffer`]; also published into
+    /// [`MEMORY_BUFFER`] by `init()` so [`Self::query`] can reach it after
+    /// the logger itself has been moved into the global registry.
+    memory_buffer: Option<MemoryBuffer>,
+    renderer: Renderer,
+    data: LoggingData,
+}
+
+/// Published by `init()` when [`TreeLogger::with_memory_buffer`] was used,
+/// so [`TreeLogger::query`] has something to read without needing a handle
+/// to the (now globally-owned) logger instance.
+static MEMORY_BUFFER: OnceLock<MemoryBuffer> = OnceLock::new();
+
+/// A completed root span's batch, stamped with the wall-clock time it was
+/// buffered at so [`MemoryBuffer::push`] can evict it once it's older than
+/// `keep`.
+#[derive(Clone)]
+struct StoredBatch {
+    stamped_at: DateTime<Utc>,
+    events: Vec<LoggingEvent>,
+}
+
+/// Rolling window of completed span trees retained for later inspection via
+/// [`TreeLogger::query`], independent of whatever gets printed to the
+/// console/file.
+#[derive(Clone)]
+struct MemoryBuffer {
+    keep: Duration,
+    entries: Arc<Mutex<VecDeque<StoredBatch>>>,
+}
+
+impl MemoryBuffer {
+    fn new(keep: Duration) -> MemoryBuffer {
+        MemoryBuffer {
+            keep,
+            entries: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    fn push(&self, events: Vec<LoggingEvent>) {
+        let now = Utc::now();
+        let keep = chrono::Duration::from_std(self.keep).unwrap_or(chrono::Duration::MAX);
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(StoredBatch {
+            stamped_at: now,
+            events,
+        });
+        while let Some(oldest) = entries.front() {
+            if now - oldest.stamped_at > keep {
+                entries.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Filter passed to [`TreeLogger::query`].
+pub struct QueryFilter {
+    /// Only events at least as severe as `min_level` are returned (e.g.
+    /// `Level::Warn` excludes `Info`/`Debug`/`Trace`).
+    pub min_level: Level,
+    /// When set, only events whose `target` matches are returned.
+    pub target: Option<Regex>,
+    /// When set, only batches buffered at or after this time are considered.
+    pub not_before: Option<DateTime<Utc>>,
+    /// Caps the number of returned events.
+    pub limit: usize,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        QueryFilter {
+            min_level: Level::Trace,
+            target: None,
+            not_before: None,
+            limit: usize::MAX,
+        }
+    }
+}
+
+/// Rendering config, split out of `TreeLogger` so it can be cloned onto the
+/// background writer thread spawned by [`TreeLogger::with_async`].
+#[derive(Clone)]
+struct Renderer {
     colors_enabled: bool,
-    use_stderr: bool,
+    target: OutputTarget,
     filter_fn: fn(&LoggingEvent) -> bool,
-    data: LoggingData,
+    filter_spec: Option<FilterSpec>,
+    format: OutputFormat,
+}
+
+/// Where rendered lines go. Replaces a plain `use_stderr` bool so a rotating
+/// file sink can be plugged in instead of the console.
+#[derive(Clone)]
+enum OutputTarget {
+    Stdout,
+    Stderr,
+    File(Arc<Mutex<FileSink>>),
+}
+
+/// A log file that rotates to `base_path.1`, `base_path.2`, ... once it
+/// exceeds `cap` bytes, the way size-capped log listeners do.
+struct FileSink {
+    writer: BufWriter<File>,
+    bytes_written: u64,
+    cap: u64,
+    base_path: PathBuf,
+}
+
+impl FileSink {
+    fn open(base_path: PathBuf, cap: u64) -> io::Result<FileSink> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&base_path)?;
+        let bytes_written = file.metadata()?.len();
+        Ok(FileSink {
+            writer: BufWriter::new(file),
+            bytes_written,
+            cap,
+            base_path,
+        })
+    }
+
+    /// Writes one line, stripping ANSI codes first since the file target is
+    /// always plain text even when `with_colors(true)` is set for the
+    /// console, then rotates if `cap` has been crossed.
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let stripped = String::from_utf8(strip(line.as_bytes())).unwrap_or_default();
+        writeln!(self.writer, "{stripped}")?;
+        self.bytes_written += stripped.len() as u64 + 1;
+
+        if self.bytes_written > self.cap {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+
+        let mut highest = 0;
+        while Self::rotated_path(&self.base_path, highest + 1).exists() {
+            highest += 1;
+        }
+        for index in (1..=highest).rev() {
+            fs::rename(
+                Self::rotated_path(&self.base_path, index),
+                Self::rotated_path(&self.base_path, index + 1),
+            )?;
+        }
+        fs::rename(&self.base_path, Self::rotated_path(&self.base_path, 1))?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.base_path)?;
+        self.writer = BufWriter::new(file);
+        self.bytes_written = 0;
+        Ok(())
+    }
+
+    fn rotated_path(base_path: &Path, index: u64) -> PathBuf {
+        let mut rotated = base_path.as_os_str().to_owned();
+        rotated.push(format!(".{index}"));
+        PathBuf::from(rotated)
+    }
+}
+
+/// A message sent to the background writer thread. Whole completed batches
+/// travel as single messages so ordering per thread is preserved.
+enum WriterMessage {
+    Batch(Vec<LoggingEvent>),
+    /// Sent by `flush()`; the writer acks via the embedded channel once
+    /// every `Batch` queued ahead of it has been rendered.
+    Flush(mpsc::Sender<()>),
+}
+
+/// Selects how [`TreeLogger`] renders buffered events.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The default padded, colorized console line.
+    #[default]
+    Text,
+    /// One JSON object per completed root span, nesting children under a
+    /// `children` array. Machine-readable, no ANSI/width logic.
+    Json,
+}
+
+/// A parsed `with_filter_spec`/`TREE_LOG` spec.
+///
+/// Grammar: `*` or a `|`-separated allowlist of root-span labels, optionally
+/// followed by `@D` (max display depth) and/or `>N` (minimum elapsed ms for
+/// the root span to be shown at all). E.g. `*@3>10` or `foo|bar@2`.
+#[derive(Debug, Clone)]
+struct FilterSpec {
+    allowed: Option<HashSet<String>>,
+    max_depth: usize,
+    threshold_ms: u128,
+}
+
+impl FilterSpec {
+    fn parse(spec: &str) -> FilterSpec {
+        let mut rest = spec.trim();
+        let mut threshold_ms = 0;
+        let mut max_depth = usize::MAX;
+
+        // `@D` and `>N` may appear in either order, so keep stripping
+        // whichever one is rightmost until neither parses anymore.
+        loop {
+            if let Some(idx) = rest.rfind('>') {
+                if let Ok(n) = rest[idx + 1..].parse::<u128>() {
+                    threshold_ms = n;
+                    rest = &rest[..idx];
+                    continue;
+                }
+            }
+
+            if let Some(idx) = rest.rfind('@') {
+                if let Ok(n) = rest[idx + 1..].parse::<usize>() {
+                    max_depth = n;
+                    rest = &rest[..idx];
+                    continue;
+                }
+            }
+
+            break;
+        }
+
+        let allowed = if rest.is_empty() || rest == "*" {
+            None
+        } else {
+            Some(rest.split('|').map(str::to_string).collect())
+        };
+
+        FilterSpec {
+            allowed,
+            max_depth,
+            threshold_ms,
+        }
+    }
+
+    /// Whether the root span (and therefore its whole buffered batch) should
+    /// be displayed at all.
+    fn allows_root(&self, root: &LoggingEvent) -> bool {
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(&root.args) {
+                return false;
+            }
+        }
+        root.elapsed.unwrap_or(0) >= self.threshold_ms
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -30,11 +307,12 @@ struct InternalLoggingData {
     events: Vec<LoggingEvent>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LoggingEvent {
     pub id: Option<usize>,
     pub indentation: usize,
     pub elapsed: Option<u128>,
+    #[serde(serialize_with = "serialize_level")]
     pub level: Level,
     pub target: String,
     pub args: String,
@@ -42,6 +320,15 @@ pub struct LoggingEvent {
     pub quiet: bool,
 }
 
+/// Serializes `Level` as its lowercase name (`"info"`, `"warn"`, ...) by
+/// lowercasing `log::Level`'s own `Display` output, which is uppercase.
+fn serialize_level<S>(level: &Level, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&level.to_string().to_lowercase())
+}
+
 impl LoggingEvent {
     fn get_args(&self) -> String {
         use ansi_term::Colour::{Cyan, Red};
@@ -190,21 +477,134 @@ impl TreeLogger {
         TreeLogger {
             default_level: LevelFilter::Trace,
             threads_enabled: false,
-            colors_enabled: false,
-            use_stderr: false,
-            filter_fn: |_| true,
+            module_levels: Vec::new(),
+            async_enabled: false,
+            sender: None,
+            memory_buffer: None,
+            renderer: Renderer {
+                colors_enabled: false,
+                target: OutputTarget::Stdout,
+                filter_fn: |_| true,
+                filter_spec: None,
+                format: OutputFormat::Text,
+            },
             data: LoggingData::default(),
         }
     }
 
-    pub fn init(self) -> Result<(), SetLoggerError> {
+    pub fn init(mut self) -> Result<(), SetLoggerError> {
+        if self.renderer.filter_spec.is_none() {
+            if let Ok(spec) = std::env::var(TREE_LOG_ENV_VAR) {
+                self.renderer.filter_spec = Some(FilterSpec::parse(&spec));
+            }
+        }
+
+        if self.async_enabled {
+            let (sender, receiver) = mpsc::sync_channel::<WriterMessage>(ASYNC_CHANNEL_CAPACITY);
+            let renderer = self.renderer.clone();
+            std::thread::Builder::new()
+                .name("tree-logger-writer".into())
+                .spawn(move || {
+                    for message in receiver {
+                        match message {
+                            WriterMessage::Batch(data) => renderer.print_data(data),
+                            WriterMessage::Flush(ack) => {
+                                renderer.flush();
+                                let _ = ack.send(());
+                            }
+                        }
+                    }
+                })
+                .expect("failed to spawn tree-logger writer thread");
+            self.sender = Some(sender);
+        }
+
+        if let Some(memory_buffer) = &self.memory_buffer {
+            let _ = MEMORY_BUFFER.set(memory_buffer.clone());
+        }
+
         log::set_max_level(self.max_level());
         log::set_boxed_logger(Box::new(self))
     }
 
     #[must_use = "You must call init() to begin logging"]
     pub fn with_filter_fn(mut self, filter_fn: fn(&LoggingEvent) -> bool) -> TreeLogger {
-        self.filter_fn = filter_fn;
+        self.renderer.filter_fn = filter_fn;
+        self
+    }
+
+    /// Moves formatting and printing onto a dedicated background thread fed
+    /// by a bounded channel, keeping `log()` off the hot path: it only
+    /// builds the `LoggingEvent` / buffers it, and on span completion sends
+    /// the whole completed batch as a single message so per-thread ordering
+    /// is preserved. Use [`Self::flush`] before shutdown to make sure the
+    /// writer has drained its queue.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_async(mut self, enable_async: bool) -> TreeLogger {
+        self.async_enabled = enable_async;
+        self
+    }
+
+    /// Retains a rolling window of completed span trees (alongside whatever
+    /// is printed) for later inspection via [`Self::query`], evicting
+    /// batches older than `keep` as new ones arrive.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_memory_buffer(mut self, keep: Duration) -> TreeLogger {
+        self.memory_buffer = Some(MemoryBuffer::new(keep));
+        self
+    }
+
+    /// Returns events buffered by [`Self::with_memory_buffer`] that match
+    /// `filter`, newest batch first. Returns an empty `Vec` if no memory
+    /// buffer was configured.
+    pub fn query(filter: QueryFilter) -> Vec<LoggingEvent> {
+        let Some(memory_buffer) = MEMORY_BUFFER.get() else {
+            return Vec::new();
+        };
+
+        let entries = memory_buffer.entries.lock().unwrap();
+        let mut matched = Vec::new();
+
+        'batches: for batch in entries.iter().rev() {
+            if let Some(not_before) = filter.not_before {
+                if batch.stamped_at < not_before {
+                    continue;
+                }
+            }
+
+            for event in &batch.events {
+                if event.level > filter.min_level {
+                    continue;
+                }
+                if let Some(target) = &filter.target {
+                    if !target.is_match(&event.target) {
+                        continue;
+                    }
+                }
+
+                if matched.len() >= filter.limit {
+                    break 'batches;
+                }
+                matched.push(event.clone());
+            }
+        }
+
+        matched
+    }
+
+    /// Parses a compact filter spec and applies it when rendering batches.
+    ///
+    /// Grammar: `*` or a `|`-separated allowlist of root-span labels selects
+    /// which root spans are emitted; an optional `@D` caps displayed
+    /// indentation depth; an optional `>N` requires the root span to have
+    /// taken at least `N` ms. For example `*@3>10` dumps every root span
+    /// that took at least 10ms, collapsing anything past indentation level 3.
+    ///
+    /// If this is never called, [`Self::init`] falls back to the `TREE_LOG`
+    /// environment variable, parsed with the same grammar.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_filter_spec(mut self, spec: &str) -> TreeLogger {
+        self.renderer.filter_spec = Some(FilterSpec::parse(spec));
         self
     }
 
@@ -214,6 +614,42 @@ impl TreeLogger {
         self
     }
 
+    /// Overrides the log level for targets beginning with `module`, e.g.
+    /// `with_module_level("hyper", LevelFilter::Warn)` silences a chatty
+    /// dependency while leaving [`Self::with_level`]'s default in place for
+    /// everything else.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_level(mut self, module: &str, level: LevelFilter) -> TreeLogger {
+        self.module_levels.push((module.to_string(), level));
+        // Sort longest-prefix-first so `effective_level` finds the most
+        // specific override with a simple linear scan.
+        self.module_levels
+            .sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        self
+    }
+
+    /// Bulk variant of [`Self::with_module_level`].
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_levels(
+        mut self,
+        levels: impl IntoIterator<Item = (impl Into<String>, LevelFilter)>,
+    ) -> TreeLogger {
+        for (module, level) in levels {
+            self = self.with_module_level(&module.into(), level);
+        }
+        self
+    }
+
+    /// Resolves the effective level for `target` by finding the longest
+    /// registered module prefix that matches it, falling back to
+    /// `default_level`.
+    fn effective_level(&self, target: &str) -> LevelFilter {
+        self.module_levels
+            .iter()
+            .find(|(module, _)| target.starts_with(module.as_str()))
+            .map_or(self.default_level, |(_, level)| *level)
+    }
+
     #[must_use = "You must call init() to begin logging"]
     pub fn with_threads(mut self, enable_threads: bool) -> TreeLogger {
         self.threads_enabled = enable_threads;
@@ -223,14 +659,54 @@ impl TreeLogger {
     /// Control whether messages are colored or not.
     #[must_use = "You must call init() to begin logging"]
     pub fn with_colors(mut self, enable_colors: bool) -> TreeLogger {
-        self.colors_enabled = enable_colors;
+        self.renderer.colors_enabled = enable_colors;
+        self
+    }
+
+    /// Writes rendered lines to stderr instead of stdout.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_stderr(mut self, enable_stderr: bool) -> TreeLogger {
+        self.renderer.target = if enable_stderr {
+            OutputTarget::Stderr
+        } else {
+            OutputTarget::Stdout
+        };
+        self
+    }
+
+    /// Writes to `path` instead of the console, rotating to `path.1`,
+    /// `path.2`, ... once the current file exceeds `capacity_bytes`. ANSI
+    /// codes are stripped even if [`Self::with_colors`] is enabled, since
+    /// the console and file targets are mutually exclusive.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_file(mut self, path: impl Into<PathBuf>, capacity_bytes: u64) -> TreeLogger {
+        let sink =
+            FileSink::open(path.into(), capacity_bytes).expect("failed to open tree-logger file");
+        self.renderer.target = OutputTarget::File(Arc::new(Mutex::new(sink)));
         self
     }
 
+    /// Selects the output format, e.g. [`OutputFormat::Json`] for structured
+    /// output suitable for log shippers and dashboards.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_format(mut self, format: OutputFormat) -> TreeLogger {
+        self.renderer.format = format;
+        self
+    }
+
+    /// Returns the maximum level across the default and all module
+    /// overrides, so that `log::set_max_level` doesn't filter records before
+    /// they reach us for per-module resolution.
     pub fn max_level(&self) -> LevelFilter {
-        self.default_level
+        self.module_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default_level, |acc, level| acc.max(level))
     }
 
+}
+
+impl Renderer {
     fn get_level_string(&self, level: Level) -> String {
         let level_string = format!("{:<5}", level.to_string());
         if self.colors_enabled {
@@ -260,8 +736,31 @@ impl TreeLogger {
             return;
         }
 
+        if let Some(filter_spec) = &self.filter_spec {
+            if !filter_spec.allows_root(&data[0]) {
+                return;
+            }
+        }
+
+        let events: Vec<&LoggingEvent> = data
+            .iter()
+            .filter(|e| (self.filter_fn)(e))
+            .filter(|e| {
+                self.filter_spec
+                    .as_ref()
+                    .is_none_or(|filter_spec| e.indentation <= filter_spec.max_depth)
+            })
+            .collect();
+
+        match self.format {
+            OutputFormat::Text => self.print_text(&events),
+            OutputFormat::Json => self.print_json(&events),
+        }
+    }
+
+    fn print_text(&self, events: &[&LoggingEvent]) {
         let terminal_width = termsize::get().unwrap_or(Size { rows: 0, cols: 0 }).cols as usize;
-        for record in data.iter().filter(|e| (self.filter_fn)(e)) {
+        for record in events {
             let left = format!(
                 "{} {:indent$}{}",
                 self.get_level_string(record.level),
@@ -287,18 +786,105 @@ impl TreeLogger {
                 left
             };
 
-            if self.use_stderr {
-                eprintln!("{}", message);
+            self.write_line(&message);
+        }
+    }
+
+    /// Emits one JSON object per completed root span, nesting child events
+    /// under a `children` array built from their `indentation` depths.
+    fn print_json(&self, events: &[&LoggingEvent]) {
+        for root in build_json_tree(events) {
+            let line = match serde_json::to_string(&root) {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+
+            self.write_line(&line);
+        }
+    }
+
+    fn write_line(&self, line: &str) {
+        match &self.target {
+            OutputTarget::Stdout => println!("{line}"),
+            OutputTarget::Stderr => eprintln!("{line}"),
+            OutputTarget::File(sink) => {
+                if let Err(err) = sink.lock().unwrap().write_line(line) {
+                    eprintln!("tree-logger: failed to write to log file: {err}");
+                }
+            }
+        }
+    }
+
+    /// Flushes the underlying writer, most importantly the `File` target's
+    /// `BufWriter`, which (unlike stdout/stderr) doesn't flush on its own.
+    fn flush(&self) {
+        match &self.target {
+            OutputTarget::Stdout => {
+                let _ = io::stdout().flush();
+            }
+            OutputTarget::Stderr => {
+                let _ = io::stderr().flush();
+            }
+            OutputTarget::File(sink) => {
+                if let Err(err) = sink.lock().unwrap().writer.flush() {
+                    eprintln!("tree-logger: failed to flush log file: {err}");
+                }
+            }
+        }
+    }
+}
+
+/// One node of the JSON span tree, mirroring a [`LoggingEvent`] plus its
+/// nested children (reconstructed from the flat, indentation-tagged batch).
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    #[serde(flatten)]
+    event: &'a LoggingEvent,
+    children: Vec<JsonNode<'a>>,
+}
+
+/// Rebuilds the span tree from a flat, order-preserved batch of events using
+/// their `indentation` as a stack depth: an event nests under the most
+/// recent event with a strictly smaller indentation.
+fn build_json_tree<'a>(events: &[&'a LoggingEvent]) -> Vec<JsonNode<'a>> {
+    let mut stack: Vec<(usize, JsonNode)> = Vec::new();
+    let mut roots = Vec::new();
+
+    for event in events {
+        while let Some((indentation, _)) = stack.last() {
+            if *indentation >= event.indentation {
+                let (_, finished) = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some((_, parent)) => parent.children.push(finished),
+                    None => roots.push(finished),
+                }
             } else {
-                println!("{}", message);
+                break;
             }
         }
+
+        stack.push((
+            event.indentation,
+            JsonNode {
+                event,
+                children: Vec::new(),
+            },
+        ));
+    }
+
+    while let Some((_, finished)) = stack.pop() {
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(finished),
+            None => roots.push(finished),
+        }
     }
+
+    roots
 }
 
 impl Log for TreeLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level().to_level_filter() <= self.default_level
+        metadata.level().to_level_filter() <= self.effective_level(metadata.target())
     }
 
     fn log(&self, record: &Record) {
@@ -337,18 +923,202 @@ impl Log for TreeLogger {
         }
 
         if let Some(data) = self.data.get_data_to_log() {
-            self.print_data(data);
+            if let Some(memory_buffer) = &self.memory_buffer {
+                memory_buffer.push(data.clone());
+            }
+
+            match &self.sender {
+                Some(sender) => {
+                    let _ = sender.send(WriterMessage::Batch(data));
+                }
+                None => self.renderer.print_data(data),
+            }
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        let Some(sender) = &self.sender else {
+            self.renderer.flush();
+            return;
+        };
+
+        let (ack_sender, ack_receiver) = mpsc::channel();
+        let sent = sender.send(WriterMessage::Flush(ack_sender)).is_ok();
+        if sent {
+            let _ = ack_receiver.recv();
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
 
-    // TODO: how to test?
     #[test]
-    fn test_module_levels_denylist() {}
+    fn test_module_levels_denylist() {
+        let logger = TreeLogger::new()
+            .with_level(LevelFilter::Trace)
+            .with_module_level("hyper", LevelFilter::Warn)
+            .with_module_level("hyper::client", LevelFilter::Trace);
+
+        // Falls back to the default level when no prefix matches.
+        assert_eq!(logger.effective_level("my_crate::db"), LevelFilter::Trace);
+
+        // Matches the registered prefix.
+        assert_eq!(logger.effective_level("hyper::pool"), LevelFilter::Warn);
+
+        // The longer, more specific prefix wins over the shorter one.
+        assert_eq!(
+            logger.effective_level("hyper::client::conn"),
+            LevelFilter::Trace
+        );
+
+        // max_level() must cover every configured override, not just the
+        // default, so the global log::set_max_level doesn't clip records.
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_filter_spec_parse_order_independent() {
+        // `@D` before `>N` ...
+        let spec = FilterSpec::parse("*@3>10");
+        assert_eq!(spec.max_depth, 3);
+        assert_eq!(spec.threshold_ms, 10);
+
+        // ... and `>N` before `@D` must parse identically.
+        let spec = FilterSpec::parse("*>10@3");
+        assert_eq!(spec.max_depth, 3);
+        assert_eq!(spec.threshold_ms, 10);
+        assert!(spec.allowed.is_none());
+    }
+
+    #[test]
+    fn test_filter_spec_parse_allowlist() {
+        let spec = FilterSpec::parse("foo|bar@2");
+        assert_eq!(
+            spec.allowed,
+            Some(["foo".to_string(), "bar".to_string()].into_iter().collect())
+        );
+        assert_eq!(spec.max_depth, 2);
+        assert_eq!(spec.threshold_ms, 0);
+    }
+
+    fn test_event(indentation: usize, args: &str) -> LoggingEvent {
+        LoggingEvent {
+            id: None,
+            indentation,
+            elapsed: None,
+            level: Level::Info,
+            target: "test".into(),
+            args: args.into(),
+            thread: "".into(),
+            quiet: false,
+        }
+    }
+
+    #[test]
+    fn test_build_json_tree_nests_by_indentation() {
+        // root
+        //   child
+        //     grandchild
+        //   sibling
+        let events = [
+            test_event(0, "root"),
+            test_event(1, "child"),
+            test_event(2, "grandchild"),
+            test_event(1, "sibling"),
+        ];
+        let refs: Vec<&LoggingEvent> = events.iter().collect();
+
+        let roots = build_json_tree(&refs);
+        assert_eq!(roots.len(), 1);
+        let root = &roots[0];
+        assert_eq!(root.event.args, "root");
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].event.args, "child");
+        assert_eq!(root.children[0].children.len(), 1);
+        assert_eq!(root.children[0].children[0].event.args, "grandchild");
+        assert_eq!(root.children[1].event.args, "sibling");
+        assert!(root.children[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_memory_buffer_evicts_old_batches() {
+        let buffer = MemoryBuffer::new(Duration::from_millis(20));
+        buffer.push(vec![test_event(0, "old")]);
+        std::thread::sleep(Duration::from_millis(40));
+        buffer.push(vec![test_event(0, "new")]);
+
+        let entries = buffer.entries.lock().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].events[0].args, "new");
+    }
+
+    #[test]
+    fn test_query_filters_by_level_target_and_limit() {
+        let logger = TreeLogger::new().with_memory_buffer(Duration::from_secs(60));
+        let buffer = logger.memory_buffer.clone().unwrap();
+
+        buffer.push(vec![
+            LoggingEvent {
+                level: Level::Info,
+                target: "my_crate::db".into(),
+                ..test_event(0, "root")
+            },
+            LoggingEvent {
+                level: Level::Warn,
+                target: "my_crate::net".into(),
+                ..test_event(1, "child")
+            },
+        ]);
+        let _ = MEMORY_BUFFER.set(buffer);
+
+        // min_level excludes the lower-severity Info event.
+        let warn_only = TreeLogger::query(QueryFilter {
+            min_level: Level::Warn,
+            ..Default::default()
+        });
+        assert_eq!(warn_only.len(), 1);
+        assert_eq!(warn_only[0].args, "child");
+
+        // target restricts to matching events only.
+        let db_only = TreeLogger::query(QueryFilter {
+            target: Some(Regex::new("^my_crate::db$").unwrap()),
+            ..Default::default()
+        });
+        assert_eq!(db_only.len(), 1);
+        assert_eq!(db_only[0].args, "root");
+
+        // limit caps the number of returned events.
+        let limited = TreeLogger::query(QueryFilter {
+            limit: 1,
+            ..Default::default()
+        });
+        assert_eq!(limited.len(), 1);
+    }
+
+    #[test]
+    fn test_file_sink_rotates_on_cap() {
+        let path = std::env::temp_dir().join(format!(
+            "tree-logger-test-{}-{:?}.log",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let rotated = FileSink::rotated_path(&path, 1);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&rotated);
+
+        let mut sink = FileSink::open(path.clone(), 10).unwrap();
+        sink.write_line("first").unwrap(); // 6 bytes, under the 10-byte cap
+        sink.write_line("second").unwrap(); // pushes cumulative past the cap, rotates
+
+        // The rotated file holds everything written up to and including the
+        // line that crossed the cap; the base path was truncated and
+        // reopened fresh.
+        assert_eq!(fs::read_to_string(&rotated).unwrap(), "first\nsecond\n");
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&rotated);
+    }
 }